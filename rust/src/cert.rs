@@ -63,7 +63,8 @@
 //! # 1. Generate an unlock challenge (the exact fastboot command is device-specific).
 //! $ fastboot oem get-auth-unlock-challenge
 //!
-//! # Internally, the device calls `cert_generate_unlock_challenge()` to generate the token.
+//! # Internally, the device calls `cert_generate_unlock_challenge()` to generate the token, and
+//! # must retain the returned challenge for comparison in step 5.
 //!
 //! # 2. Download the challenge token from the device.
 //! $ fastboot get_staged /tmp/challenge.bin
@@ -80,10 +81,22 @@
 //! # 5. Unlock the device (the exact fastboot command is device-specific).
 //! $ fastboot oem auth-unlock
 //!
-//! # Internally, the device calls `cert_validate_unlock_credential()` to verify the credential.
+//! # Internally, the device calls `cert_validate_unlock_credential()` with the retained challenge
+//! # to verify the credential.
 //! ```
 
 use crate::{IoError, IoResult, Ops};
+use alloc::boxed::Box;
+use avb_bindgen::{
+    avb_cert_generate_unlock_challenge, avb_cert_validate_unlock_credential,
+    avb_cert_validate_vbmeta_public_key, avb_cert_validate_vbmeta_public_key_for_partition,
+    AvbCertOps, AvbIOResult, AvbOps,
+};
+use core::{
+    ffi::c_void,
+    mem::{zeroed, MaybeUninit},
+    ptr, slice,
+};
 
 /// libavb_cert permanent attributes.
 pub use avb_bindgen::AvbCertPermanentAttributes as CertPermanentAttributes;
@@ -97,6 +110,9 @@ pub use avb_bindgen::AvbCertUnlockCredential as CertUnlockCredential;
 /// Size in bytes of a SHA256 digest.
 pub const SHA256_DIGEST_SIZE: usize = avb_bindgen::AVB_SHA256_DIGEST_SIZE as usize;
 
+/// Size in bytes of a partition GUID.
+pub const CERT_GUID_SIZE: usize = 16;
+
 /// Product intermediate key (PIK) rollback index location.
 ///
 /// If using libavb_cert, make sure no vbmetas use this location, it must be reserved for the PIK.
@@ -176,6 +192,164 @@ pub trait CertOps<'a>: Ops<'a> {
     fn get_random(&mut self, bytes: &mut [u8]) -> IoResult<()>;
 }
 
+/// Bridges a `&mut dyn CertOps` into the C `AvbCertOps` struct that libavb_cert expects.
+///
+/// libavb_cert calls back into device-specific code via `extern "C"` function pointers on
+/// `AvbCertOps` rather than through a Rust trait, so this stashes the `CertOps` trait object
+/// pointer in the owning `AvbOps.user_data` field, and the trampoline functions below recover it
+/// and forward the call.
+///
+/// The `AvbOps` embedded here exists only to carry `user_data` through to the trampolines; none
+/// of its other callbacks are invoked by the libavb_cert entry points.
+///
+/// `avb_ops.user_data` points at the heap allocation backing `cert_ops` rather than at a field of
+/// this struct itself, so `ScopedAvbCertOps` is safe to move or return by value: the `Box`'s
+/// address stays stable regardless of where the struct holding it lives.
+struct ScopedAvbCertOps<'a> {
+    cert_ops: Box<*mut (dyn CertOps<'a> + 'a)>,
+    avb_ops: AvbOps,
+    avb_cert_ops: AvbCertOps,
+}
+
+impl<'a> ScopedAvbCertOps<'a> {
+    /// Creates a new bridge wrapping the given `CertOps` implementation.
+    fn new(cert_ops: &'a mut dyn CertOps<'a>) -> Self {
+        let cert_ops = Box::new(cert_ops as *mut dyn CertOps<'a>);
+
+        // SAFETY: `AvbOps` is a C struct of primitives and optional function pointers, all of
+        // which are valid when zeroed; only `user_data` is read by the libavb_cert entry points.
+        let mut avb_ops: AvbOps = unsafe { zeroed() };
+        avb_ops.user_data = Box::as_ref(&cert_ops) as *const _ as *mut c_void;
+
+        let avb_cert_ops = AvbCertOps {
+            // Filled in by `as_avb_cert_ops()`: `avb_ops` moves into `Self` below, so a pointer
+            // taken now would dangle as soon as this constructor returns.
+            ops: ptr::null_mut(),
+            read_permanent_attributes: Some(read_permanent_attributes_trampoline),
+            read_permanent_attributes_hash: Some(read_permanent_attributes_hash_trampoline),
+            set_key_version: Some(set_key_version_trampoline),
+            get_random: Some(get_random_trampoline),
+        };
+
+        Self {
+            cert_ops,
+            avb_ops,
+            avb_cert_ops,
+        }
+    }
+
+    /// Returns the raw `AvbCertOps` pointer to pass to libavb_cert C entry points.
+    fn as_avb_cert_ops(&mut self) -> *mut AvbCertOps {
+        self.avb_cert_ops.ops = &mut self.avb_ops;
+        &mut self.avb_cert_ops
+    }
+}
+
+/// Recovers the `&mut dyn CertOps` stashed by `ScopedAvbCertOps::new()` in `(*ops).ops.user_data`.
+///
+/// # Safety
+/// `ops` must be a valid, non-null pointer to an `AvbCertOps` created by
+/// `ScopedAvbCertOps::new()`, and the `CertOps` it was created from must still be alive and must
+/// not be concurrently borrowed elsewhere.
+unsafe fn as_cert_ops<'a>(ops: *mut AvbCertOps) -> &'a mut dyn CertOps<'a> {
+    let cert_ops = (*(*ops).ops).user_data as *mut *mut dyn CertOps<'a>;
+    &mut **cert_ops
+}
+
+/// Converts a `CertOps` callback result to the `AvbIOResult` libavb_cert expects.
+fn to_avb_io_result(result: IoResult<()>) -> AvbIOResult {
+    match result {
+        Ok(()) => AvbIOResult::AVB_IO_RESULT_OK,
+        Err(e) => to_avb_io_error_result(e),
+    }
+}
+
+/// Converts an `IoError` to the matching `AvbIOResult` error variant.
+fn to_avb_io_error_result(error: IoError) -> AvbIOResult {
+    match error {
+        IoError::Oom => AvbIOResult::AVB_IO_RESULT_ERROR_OOM,
+        IoError::Io => AvbIOResult::AVB_IO_RESULT_ERROR_IO,
+        IoError::NoSuchPartition => AvbIOResult::AVB_IO_RESULT_ERROR_NO_SUCH_PARTITION,
+        IoError::RangeOutsidePartition => AvbIOResult::AVB_IO_RESULT_ERROR_RANGE_OUTSIDE_PARTITION,
+        IoError::NoSuchValue => AvbIOResult::AVB_IO_RESULT_ERROR_NO_SUCH_VALUE,
+        IoError::InvalidValueSize => AvbIOResult::AVB_IO_RESULT_ERROR_INVALID_VALUE_SIZE,
+        IoError::InsufficientSpace => AvbIOResult::AVB_IO_RESULT_ERROR_INSUFFICIENT_SPACE,
+        IoError::Unsupported | IoError::NotImplemented => {
+            AvbIOResult::AVB_IO_RESULT_ERROR_UNSUPPORTED
+        }
+    }
+}
+
+/// Converts an `AvbIOResult` back to an `IoResult<()>`, for surfacing libavb_cert's own errors.
+fn to_io_result(result: AvbIOResult) -> IoResult<()> {
+    match result {
+        AvbIOResult::AVB_IO_RESULT_OK => Ok(()),
+        AvbIOResult::AVB_IO_RESULT_ERROR_OOM => Err(IoError::Oom),
+        AvbIOResult::AVB_IO_RESULT_ERROR_IO => Err(IoError::Io),
+        AvbIOResult::AVB_IO_RESULT_ERROR_NO_SUCH_PARTITION => Err(IoError::NoSuchPartition),
+        AvbIOResult::AVB_IO_RESULT_ERROR_RANGE_OUTSIDE_PARTITION => {
+            Err(IoError::RangeOutsidePartition)
+        }
+        AvbIOResult::AVB_IO_RESULT_ERROR_NO_SUCH_VALUE => Err(IoError::NoSuchValue),
+        AvbIOResult::AVB_IO_RESULT_ERROR_INVALID_VALUE_SIZE => Err(IoError::InvalidValueSize),
+        AvbIOResult::AVB_IO_RESULT_ERROR_INSUFFICIENT_SPACE => Err(IoError::InsufficientSpace),
+        AvbIOResult::AVB_IO_RESULT_ERROR_UNSUPPORTED => Err(IoError::Unsupported),
+    }
+}
+
+/// Trampoline for `AvbCertOps.read_permanent_attributes`; forwards to `CertOps`.
+extern "C" fn read_permanent_attributes_trampoline(
+    ops: *mut AvbCertOps,
+    attributes: *mut CertPermanentAttributes,
+) -> AvbIOResult {
+    // SAFETY: `ops` was created by `ScopedAvbCertOps::new()` and `attributes` is a valid output
+    // pointer provided by libavb_cert for the duration of this call.
+    unsafe { to_avb_io_result(as_cert_ops(ops).read_permanent_attributes(&mut *attributes)) }
+}
+
+/// Trampoline for `AvbCertOps.read_permanent_attributes_hash`; forwards to `CertOps`.
+extern "C" fn read_permanent_attributes_hash_trampoline(
+    ops: *mut AvbCertOps,
+    hash: *mut u8,
+) -> AvbIOResult {
+    // SAFETY: `ops` was created by `ScopedAvbCertOps::new()` and `hash` points to a buffer of at
+    // least `SHA256_DIGEST_SIZE` bytes, as required by libavb_cert.
+    unsafe {
+        let cert_ops = as_cert_ops(ops);
+        match cert_ops.read_permanent_attributes_hash() {
+            Ok(digest) => {
+                slice::from_raw_parts_mut(hash, SHA256_DIGEST_SIZE).copy_from_slice(&digest);
+                AvbIOResult::AVB_IO_RESULT_OK
+            }
+            Err(e) => to_avb_io_error_result(e),
+        }
+    }
+}
+
+/// Trampoline for `AvbCertOps.set_key_version`; forwards to `CertOps`.
+extern "C" fn set_key_version_trampoline(
+    ops: *mut AvbCertOps,
+    rollback_index_location: usize,
+    key_version: u64,
+) {
+    // SAFETY: `ops` was created by `ScopedAvbCertOps::new()`.
+    unsafe { as_cert_ops(ops).set_key_version(rollback_index_location, key_version) }
+}
+
+/// Trampoline for `AvbCertOps.get_random`; forwards to `CertOps`.
+extern "C" fn get_random_trampoline(
+    ops: *mut AvbCertOps,
+    num_bytes: usize,
+    output: *mut u8,
+) -> AvbIOResult {
+    // SAFETY: `ops` was created by `ScopedAvbCertOps::new()` and `output` points to a buffer of at
+    // least `num_bytes` bytes, as required by libavb_cert.
+    unsafe {
+        let buffer = slice::from_raw_parts_mut(output, num_bytes);
+        to_avb_io_result(as_cert_ops(ops).get_random(buffer))
+    }
+}
+
 /// Certificate-based vbmeta key validation.
 ///
 /// This can be called from `validate_vbmeta_public_key()` or `validate_public_key_for_partition()`
@@ -205,12 +379,124 @@ pub trait CertOps<'a>: Ops<'a> {
 /// # Returns
 /// True if the given key is valid, false if it is not, `IoError` on error.
 pub fn cert_validate_vbmeta_public_key(
-    _ops: &mut dyn CertOps,
-    _public_key: &[u8],
-    _public_key_metadata: Option<&[u8]>,
+    ops: &mut dyn CertOps,
+    public_key: &[u8],
+    public_key_metadata: Option<&[u8]>,
 ) -> IoResult<bool> {
-    // TODO(b/320543206): implement
-    Err(IoError::NotImplemented)
+    let mut scoped_ops = ScopedAvbCertOps::new(ops);
+    let (metadata_ptr, metadata_size) = match public_key_metadata {
+        Some(metadata) => (metadata.as_ptr(), metadata.len()),
+        None => (ptr::null(), 0),
+    };
+    let mut key_is_trusted = false;
+
+    // SAFETY: `avb_cert_validate_vbmeta_public_key()` only reads `public_key` and
+    // `public_key_metadata` for the duration of this call, writes a single `bool` through
+    // `key_is_trusted`, and `scoped_ops` bridges its callbacks back into `ops`.
+    let result = unsafe {
+        avb_cert_validate_vbmeta_public_key(
+            scoped_ops.as_avb_cert_ops(),
+            public_key.as_ptr(),
+            public_key.len(),
+            metadata_ptr,
+            metadata_size,
+            &mut key_is_trusted,
+        )
+    };
+
+    to_io_result(result).map(|()| key_is_trusted)
+}
+
+/// Partition-binding information for a vbmeta public key validated via
+/// `cert_validate_vbmeta_public_key_for_partition()`.
+///
+/// This allows a device to enforce that a given signing key is only valid for the specific
+/// partition it was certified for, rather than any partition in the image.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CertValidatePublicKeyForPartitionInfo {
+    /// Rollback index location holding this key's version.
+    pub rollback_index_location: usize,
+
+    /// Partition GUID this key is authorized to sign, as raw big-endian bytes.
+    #[cfg(not(feature = "uuid"))]
+    pub partition_guid: [u8; CERT_GUID_SIZE],
+
+    /// Partition GUID this key is authorized to sign.
+    #[cfg(feature = "uuid")]
+    pub partition_guid: uuid::Uuid,
+}
+
+/// Certificate-based partition-scoped vbmeta key validation.
+///
+/// This is the partition-aware counterpart to `cert_validate_vbmeta_public_key()`, for use from
+/// `Ops::validate_public_key_for_partition()` when a device needs to confirm not just that the
+/// key is trusted, but that it's trusted specifically for the partition being verified:
+///
+/// ```ignore
+/// impl avb::Ops for MyOps {
+///   fn validate_public_key_for_partition(
+///     &mut self,
+///     partition: &str,
+///     public_key: &[u8],
+///     public_key_metadata: Option<&[u8]>,
+///   ) -> IoResult<PublicKeyForPartitionInfo> {
+///     let info = cert_validate_vbmeta_public_key_for_partition(self, public_key, public_key_metadata)?
+///         .filter(|info| info.partition_guid == self.expected_guid(partition))
+///         .ok_or(IoError::Io)?;
+///     Ok(PublicKeyForPartitionInfo { trusted: true, rollback_index_location: info.rollback_index_location })
+///   }
+/// }
+/// ```
+///
+/// # Arguments
+/// * `ops`: the `CertOps` callback implementations.
+/// * `public_key`: the public key.
+/// * `public_key_metadata`: public key metadata.
+///
+/// # Returns
+/// `Some(info)` with the partition binding if the given key is valid, `None` if it is not,
+/// `IoError` on error.
+pub fn cert_validate_vbmeta_public_key_for_partition(
+    ops: &mut dyn CertOps,
+    public_key: &[u8],
+    public_key_metadata: Option<&[u8]>,
+) -> IoResult<Option<CertValidatePublicKeyForPartitionInfo>> {
+    let mut scoped_ops = ScopedAvbCertOps::new(ops);
+    let (metadata_ptr, metadata_size) = match public_key_metadata {
+        Some(metadata) => (metadata.as_ptr(), metadata.len()),
+        None => (ptr::null(), 0),
+    };
+    let mut key_is_trusted = false;
+    let mut rollback_index_location: usize = 0;
+    let mut partition_guid = [0u8; CERT_GUID_SIZE];
+
+    // SAFETY: `avb_cert_validate_vbmeta_public_key_for_partition()` only reads `public_key` and
+    // `public_key_metadata` for the duration of this call, writes through the given output
+    // pointers, and `scoped_ops` bridges its callbacks back into `ops`.
+    let result = unsafe {
+        avb_cert_validate_vbmeta_public_key_for_partition(
+            scoped_ops.as_avb_cert_ops(),
+            public_key.as_ptr(),
+            public_key.len(),
+            metadata_ptr,
+            metadata_size,
+            &mut key_is_trusted,
+            &mut rollback_index_location,
+            partition_guid.as_mut_ptr(),
+        )
+    };
+    to_io_result(result)?;
+
+    if !key_is_trusted {
+        return Ok(None);
+    }
+    Ok(Some(CertValidatePublicKeyForPartitionInfo {
+        rollback_index_location,
+        #[cfg(not(feature = "uuid"))]
+        partition_guid,
+        #[cfg(feature = "uuid")]
+        partition_guid: uuid::Uuid::from_bytes(partition_guid),
+    }))
 }
 
 /// Generates a challenge for authenticated unlock.
@@ -219,25 +505,48 @@ pub fn cert_validate_vbmeta_public_key(
 ///
 /// The user can sign the resulting token via `avbtool make_cert_unlock_credential`.
 ///
+/// The returned challenge must be retained by the caller (e.g. cached in RAM) for as long as the
+/// unlock attempt is in progress, since `cert_validate_unlock_credential()` needs the original
+/// challenge to confirm that the signed credential it receives corresponds to the one actually
+/// issued here, rather than a stale or forged one.
+///
 /// # Arguments
 /// * `cert_ops`: the `CertOps` callback implementations.
 ///
 /// # Returns
-/// The challenge to sign with the PUK, or `IoError` on `cert_ops` failure.
-pub fn cert_generate_unlock_challenge(
-    _cert_ops: &mut dyn CertOps,
-) -> IoResult<CertUnlockChallenge> {
-    // TODO(b/320543206): implement
-    Err(IoError::NotImplemented)
+/// The challenge to sign with the PUK, or `IoError` on `cert_ops` failure. In particular, this
+/// returns `IoError::Unsupported` if `cert_ops.get_random()` is itself unimplemented, since
+/// authenticated unlock is not available without a random source.
+///
+/// Note this is `Unsupported` rather than `NotImplemented`: `AvbIOResult` has no "not implemented"
+/// variant of its own, so the callback trampolines convert `IoError::NotImplemented` into the
+/// same `AVB_IO_RESULT_ERROR_UNSUPPORTED` value as `IoError::Unsupported`, and that's what comes
+/// back out. The original `NotImplemented` is not surfaced.
+pub fn cert_generate_unlock_challenge(cert_ops: &mut dyn CertOps) -> IoResult<CertUnlockChallenge> {
+    let mut scoped_ops = ScopedAvbCertOps::new(cert_ops);
+    let mut challenge = MaybeUninit::uninit();
+
+    // SAFETY: `avb_cert_generate_unlock_challenge()` fully initializes `challenge` on success, and
+    // `scoped_ops` bridges its callbacks back into `cert_ops`.
+    let result = unsafe {
+        avb_cert_generate_unlock_challenge(scoped_ops.as_avb_cert_ops(), challenge.as_mut_ptr())
+    };
+
+    // SAFETY: `challenge` is only read once `result` confirms it was initialized.
+    to_io_result(result).map(|()| unsafe { challenge.assume_init() })
 }
 
 /// Validates a signed credential for authenticated unlock.
 ///
 /// Used to check that an unlock credential was properly signed with the PUK according to the
-/// device's permanent attributes.
+/// device's permanent attributes, and that it is signed over the exact challenge that was issued
+/// by `cert_generate_unlock_challenge()`, rejecting stale or forged credentials.
 ///
 /// # Arguments
 /// * `cert_ops`: the `CertOps` callback implementations.
+/// * `challenge`: the challenge previously returned by `cert_generate_unlock_challenge()` for this
+///                unlock attempt; the caller is responsible for retaining this until the
+///                credential is available to check.
 /// * `credential`: the signed unlock credential to verify.
 ///
 /// # Returns
@@ -245,9 +554,67 @@ pub fn cert_generate_unlock_challenge(
 /// * `Ok(false)` if it failed validation
 /// * `Err(IoError)` on `cert_ops` failure
 pub fn cert_validate_unlock_credential(
-    _cert_ops: &mut dyn CertOps,
-    _credential: &CertUnlockCredential,
+    cert_ops: &mut dyn CertOps,
+    challenge: &CertUnlockChallenge,
+    credential: &CertUnlockCredential,
 ) -> IoResult<bool> {
-    // TODO(b/320543206): implement
-    Err(IoError::NotImplemented)
-}
\ No newline at end of file
+    let mut scoped_ops = ScopedAvbCertOps::new(cert_ops);
+    let mut credential_is_trusted = false;
+
+    // SAFETY: `avb_cert_validate_unlock_credential()` only reads `challenge` and `credential` for
+    // the duration of this call, writes a single `bool` through `credential_is_trusted`, and
+    // `scoped_ops` bridges its callbacks back into `cert_ops`.
+    let result = unsafe {
+        avb_cert_validate_unlock_credential(
+            scoped_ops.as_avb_cert_ops(),
+            challenge,
+            credential,
+            &mut credential_is_trusted,
+        )
+    };
+
+    to_io_result(result).map(|()| credential_is_trusted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_avb_io_result_ok_roundtrips() {
+        assert_eq!(to_avb_io_result(Ok(())), AvbIOResult::AVB_IO_RESULT_OK);
+        assert_eq!(to_io_result(AvbIOResult::AVB_IO_RESULT_OK), Ok(()));
+    }
+
+    #[test]
+    fn io_error_roundtrips_through_avb_io_result() {
+        for error in [
+            IoError::Oom,
+            IoError::Io,
+            IoError::NoSuchPartition,
+            IoError::RangeOutsidePartition,
+            IoError::NoSuchValue,
+            IoError::InvalidValueSize,
+            IoError::InsufficientSpace,
+            IoError::Unsupported,
+        ] {
+            assert_eq!(to_io_result(to_avb_io_error_result(error)), Err(error));
+        }
+    }
+
+    #[test]
+    fn not_implemented_collapses_to_unsupported() {
+        // libavb_cert's `AvbIOResult` has no "not implemented" variant of its own, so
+        // `IoError::NotImplemented` collapses into the same `AVB_IO_RESULT_ERROR_UNSUPPORTED`
+        // value as `IoError::Unsupported`, and therefore round-trips back as `Unsupported` rather
+        // than the original `NotImplemented`.
+        assert_eq!(
+            to_avb_io_error_result(IoError::NotImplemented),
+            AvbIOResult::AVB_IO_RESULT_ERROR_UNSUPPORTED
+        );
+        assert_eq!(
+            to_io_result(to_avb_io_error_result(IoError::NotImplemented)),
+            Err(IoError::Unsupported)
+        );
+    }
+}
@@ -62,6 +62,60 @@ impl<'a> KernelCommandlineDescriptor<'a> {
             commandline: from_utf8(commandline).map_err(|_| DescriptorError::InvalidUtf8)?,
         })
     }
+
+    /// Checks whether this descriptor's commandline should be applied given the current dm-verity
+    /// (hashtree) enablement state.
+    ///
+    /// # Arguments
+    /// * `hashtree_disabled`: whether dm-verity has been disabled for this boot.
+    ///
+    /// # Returns
+    /// True if this commandline should be applied: descriptors flagged
+    /// `AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED` only apply when dm-verity is
+    /// active, those flagged `AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED` only apply
+    /// when dm-verity has been disabled, and descriptors with neither flag always apply.
+    ///
+    /// These are independent exclusion conditions rather than a priority chain, matching libavb's
+    /// own handling: a descriptor carrying both flags can never apply in any hashtree state, since
+    /// each flag's condition would exclude it in the opposite state.
+    pub fn should_be_used(&self, hashtree_disabled: bool) -> bool {
+        use KernelCommandlineDescriptorFlags as Flags;
+
+        if self.flags.0 & Flags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED.0 != 0
+            && hashtree_disabled
+        {
+            return false;
+        }
+        if self.flags.0 & Flags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED.0 != 0
+            && !hashtree_disabled
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// Selects the effective kernel commandline fragments given the current dm-verity (hashtree)
+/// enablement state, so bootloaders don't need to re-implement the
+/// `USE_ONLY_IF_HASHTREE_NOT_DISABLED`/`USE_ONLY_IF_HASHTREE_DISABLED` flag logic themselves.
+///
+/// # Arguments
+/// * `descriptors`: the kernel commandline descriptors found in a vbmeta image.
+/// * `hashtree_disabled`: whether dm-verity has been disabled for this boot.
+///
+/// # Returns
+/// An iterator over the commandline fragments that should be applied, in `descriptors` order.
+pub fn effective_commandlines<'a, 'b>(
+    descriptors: impl IntoIterator<Item = &'b KernelCommandlineDescriptor<'a>>,
+    hashtree_disabled: bool,
+) -> impl Iterator<Item = &'b str>
+where
+    'a: 'b,
+{
+    descriptors
+        .into_iter()
+        .filter(move |descriptor| descriptor.should_be_used(hashtree_disabled))
+        .map(|descriptor| descriptor.commandline)
 }
 
 #[cfg(test)]
@@ -108,4 +162,76 @@ mod tests {
             DescriptorError::InvalidSize
         );
     }
+
+    fn descriptor_with_flags(
+        flags: KernelCommandlineDescriptorFlags,
+    ) -> KernelCommandlineDescriptor<'static> {
+        KernelCommandlineDescriptor {
+            flags,
+            commandline: "test_cmdline_key=test_cmdline_value",
+        }
+    }
+
+    #[test]
+    fn should_be_used_with_no_flags_is_always_used() {
+        let descriptor = descriptor_with_flags(KernelCommandlineDescriptorFlags(0));
+        assert!(descriptor.should_be_used(/*hashtree_disabled=*/ false));
+        assert!(descriptor.should_be_used(/*hashtree_disabled=*/ true));
+    }
+
+    #[test]
+    fn should_be_used_if_hashtree_not_disabled_only_when_enabled() {
+        let descriptor = descriptor_with_flags(
+            KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED,
+        );
+        assert!(descriptor.should_be_used(/*hashtree_disabled=*/ false));
+        assert!(!descriptor.should_be_used(/*hashtree_disabled=*/ true));
+    }
+
+    #[test]
+    fn should_be_used_if_hashtree_disabled_only_when_disabled() {
+        let descriptor = descriptor_with_flags(
+            KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED,
+        );
+        assert!(!descriptor.should_be_used(/*hashtree_disabled=*/ false));
+        assert!(descriptor.should_be_used(/*hashtree_disabled=*/ true));
+    }
+
+    #[test]
+    fn should_be_used_with_both_flags_set_is_never_used() {
+        let descriptor = descriptor_with_flags(KernelCommandlineDescriptorFlags(
+            KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED.0
+                | KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED.0,
+        ));
+        assert!(!descriptor.should_be_used(/*hashtree_disabled=*/ false));
+        assert!(!descriptor.should_be_used(/*hashtree_disabled=*/ true));
+    }
+
+    #[test]
+    fn effective_commandlines_filters_by_hashtree_state() {
+        let always = descriptor_with_flags(KernelCommandlineDescriptorFlags(0));
+        let only_if_enabled = KernelCommandlineDescriptor {
+            flags:
+                KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_NOT_DISABLED,
+            commandline: "only_if_enabled",
+        };
+        let only_if_disabled = KernelCommandlineDescriptor {
+            flags:
+                KernelCommandlineDescriptorFlags::AVB_KERNEL_CMDLINE_FLAGS_USE_ONLY_IF_HASHTREE_DISABLED,
+            commandline: "only_if_disabled",
+        };
+        let descriptors = [always, only_if_enabled, only_if_disabled];
+
+        let enabled: Vec<_> = effective_commandlines(&descriptors, false).collect();
+        assert_eq!(
+            enabled,
+            ["test_cmdline_key=test_cmdline_value", "only_if_enabled"]
+        );
+
+        let disabled: Vec<_> = effective_commandlines(&descriptors, true).collect();
+        assert_eq!(
+            disabled,
+            ["test_cmdline_key=test_cmdline_value", "only_if_disabled"]
+        );
+    }
 }